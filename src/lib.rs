@@ -74,6 +74,26 @@ impl Vector {
             z: self.z / len,
         }
     }
+
+    // Mirrors self around normal, as if it had bounced off a perfectly reflective surface.
+    // Assumes self and normal are unit vectors.
+    pub fn reflect(&self, normal: &Vector) -> Vector {
+        *self - 2.0 * self.dot(normal) * *normal
+    }
+
+    // Bends self through a surface per Snell's law, where eta is the ratio of refractive indices
+    // (incident over transmitted). Returns None on total internal reflection, i.e. when the angle
+    // of incidence is too steep for the ray to cross into the other medium. Assumes self and
+    // normal are unit vectors.
+    pub fn refract(&self, normal: &Vector, eta: f32) -> Option<Vector> {
+        let cosi = -self.dot(normal);
+        let k = 1.0 - eta * eta * (1.0 - cosi * cosi);
+        if k < 0.0 {
+            None
+        } else {
+            Some(eta * *self + (eta * cosi - k.sqrt()) * *normal)
+        }
+    }
 }
 
 impl Add for Vector {
@@ -144,19 +164,66 @@ impl Neg for Vector {
 pub struct Ray {
     pub pos: Vector,
     pub dir: Vector,
+    // Component-wise reciprocal of dir, cached so AABB slab tests don't have to divide per axis
+    // per node.
+    pub inv_dir: Vector,
 }
 
 impl Ray {
-    fn forwarded(&self, distance: f32) -> Ray {
+    pub fn new(pos: Vector, dir: Vector) -> Ray {
         Ray {
-            pos: self.pos + self.dir * distance,
-            dir: self.dir,
+            pos,
+            dir,
+            inv_dir: Vector {
+                x: 1.0 / dir.x,
+                y: 1.0 / dir.y,
+                z: 1.0 / dir.z,
+            },
         }
     }
 
+    fn forwarded(&self, distance: f32) -> Ray {
+        Ray::new(self.pos + self.dir * distance, self.dir)
+    }
+
     pub fn almost_equal(&self, other: &Ray) -> bool {
         self.pos.almost_equal(&other.pos) && self.dir.almost_equal(&other.dir)
     }
+
+    // Finds where self and other come closest to each other, returning the two parameters mua,
+    // mub such that `self.pos + mua * self.dir` and `other.pos + mub * other.dir` are those
+    // points. Useful for picking (e.g. turning a Camera::screen_ray into a query against an axis
+    // the user is dragging). Returns None when the rays are parallel, since then there's no single
+    // pair of closest points.
+    //
+    // Derivation: http://paulbourke.net/geometry/pointlineplane/
+    pub fn closest_approach(&self, other: &Ray) -> Option<(f32, f32)> {
+        let p1 = self.pos;
+        let p2 = self.pos + self.dir;
+        let p3 = other.pos;
+        let p4 = other.pos + other.dir;
+
+        let d = |x: Vector, y: Vector, z: Vector, w: Vector| (x - y).dot(&(z - w));
+
+        let d4321 = d(p4, p3, p2, p1);
+        let d4343 = d(p4, p3, p4, p3);
+        let denom = d(p2, p1, p2, p1) * d4343 - d4321 * d4321;
+        if denom.abs() < 0.0000001 {
+            return None;
+        }
+
+        let mua = (d(p1, p3, p4, p3) * d4321 - d(p1, p3, p2, p1) * d4343) / denom;
+        let mub = (d(p1, p3, p4, p3) + mua * d4321) / d4343;
+        Some((mua, mub))
+    }
+}
+
+// Anything a renderer can fire rays at. Letting callers hold a `Vec<Box<dyn Shape>>` means a
+// scene isn't limited to spheres any more. The BVH below groups spheres only; it stores
+// `Sphere` values and does not accept other shapes.
+pub trait Shape {
+    fn intersect_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection;
+    fn bounding_box(&self) -> AABB;
 }
 
 #[derive(Copy, Clone)]
@@ -165,49 +232,135 @@ pub struct Sphere {
     pub radius: f32,
 }
 
-impl Sphere {
-    pub fn intersect_ray(&self, ray: &Ray) -> Intersection {
-        // Math based on information found on
-        // http://kylehalladay.com/blog/tutorial/math/2013/12/24/Ray-Sphere-Intersection.html
-        //
-        let pos_to_center = self.center - ray.pos;
-        // No support for intersections with rays coming from inside the sphere at the moment.
-        if pos_to_center.len() <= self.radius {
+impl Shape for Sphere {
+    // Standard quadratic solution to the ray-sphere intersection problem. Unlike the previous
+    // geometric derivation this one falls out naturally for rays that start inside the sphere (or
+    // right on its surface) since it simply considers both roots of the quadratic instead of
+    // assuming the origin is outside.
+    //
+    // A point p is on the sphere when (p - center).dot(p - center) == radius^2. Substituting
+    // p = ray.pos + t * ray.dir and expanding gives a quadratic in t:
+    //   a*t^2 + b*t + c == 0
+    // with a = dir.dot(dir), b = 2 * diff.dot(dir), c = diff.dot(diff) - radius^2, where
+    // diff = ray.pos - center.
+    //
+    // Only hits with t in [t_min, t_max] are accepted, which lets callers cull shadow rays and
+    // avoid re-intersecting the same surface they just bounced off of.
+    fn intersect_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let diff = ray.pos - self.center;
+        let a = ray.dir.dot(&ray.dir);
+        let b = 2.0 * diff.dot(&ray.dir);
+        let c = diff.dot(&diff) - self.radius.powf(2.0);
+        let disc = b.powf(2.0) - 4.0 * a * c;
+        if disc < 0.0 {
             return Intersection::None;
         }
-        // tcenter is how far along the ray dir we need to go in order for the line orthogonal to
-        // the ray to cross the sphere's center. Let's call that point on the ray C.
-        let tcenter = pos_to_center.dot(&ray.dir);
-        // The sphere is in the opposite direction.
-        if tcenter < 0.0 {
+        let sq = disc.sqrt();
+        // Try the near root first; if it's outside the accepted range fall back to the far root,
+        // which is what we get when the ray starts inside the sphere.
+        let t0 = (-b - sq) / (2.0 * a);
+        let t1 = (-b + sq) / (2.0 * a);
+        let t = if t_min <= t0 && t0 <= t_max {
+            t0
+        } else if t_min <= t1 && t1 <= t_max {
+            t1
+        } else {
             return Intersection::None;
+        };
+        let point = ray.forwarded(t).pos;
+        let mut normal = (point - self.center).normalized();
+        // If the hit is on the far side (e.g. the ray started inside the sphere) the outward normal
+        // points along the ray, out of the volume we're leaving; flip it so it always faces the ray.
+        if normal.dot(&ray.dir) > 0.0 {
+            normal = -normal;
         }
-        // We now have a right triangle with [ray.pos C] being one of its leg and [ray.pos
-        // sphere.center] being its hypotenuse. The distance between C and self.center is what we
-        // need to find out and its the remaining leg of the triangle – let's use the Pythagorean
-        // theorem. We'll call the [C self.center] distance d.
-        let d = (pos_to_center.len().powf(2.0) - tcenter.powf(2.0)).sqrt();
-        // If we miss the sphere totally the distance d will be greater than the radius, let's bail
-        // in that case.
-        if d > self.radius {
+        Intersection::Hit { t, point, normal }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let r = Vector {
+            x: self.radius,
+            y: self.radius,
+            z: self.radius,
+        };
+        AABB {
+            min: self.center - r,
+            max: self.center + r,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Triangle {
+    pub a: Vector,
+    pub b: Vector,
+    pub c: Vector,
+}
+
+const MOLLER_TRUMBORE_EPSILON: f32 = 0.0000001;
+
+impl Shape for Triangle {
+    // Moller-Trumbore: solves for the barycentric coordinates (u, v) of the hit point and the
+    // distance t directly, without first computing the plane the triangle lies on.
+    fn intersect_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let e1 = self.b - self.a;
+        let e2 = self.c - self.a;
+        let p = ray.dir.cross(&e2);
+        let det = e1.dot(&p);
+        // A near-zero determinant means the ray is parallel to the triangle's plane.
+        if det.abs() < MOLLER_TRUMBORE_EPSILON {
+            return Intersection::None;
+        }
+        let inv = 1.0 / det;
+
+        let tvec = ray.pos - self.a;
+        let u = tvec.dot(&p) * inv;
+        if u < 0.0 || u > 1.0 {
+            return Intersection::None;
+        }
+
+        let qvec = tvec.cross(&e1);
+        let v = ray.dir.dot(&qvec) * inv;
+        if v < 0.0 || u + v > 1.0 {
             return Intersection::None;
         }
-        // Now we have two right triangles with self.radius being its hypotenuse and d forming one
-        // of its legs. The remaining leg is a distance tdelta that we'll use to move forward and
-        // backward along the ray starting with point C in order to get two points at which we
-        // intersect the sphere. Again – just Pythagorean theorem at work here.
-        let tdelta = (self.radius.powf(2.0) - d.powf(2.0)).sqrt();
-        // We can now calculate two points at which we cross the sphere, but we only need the
-        // closer one so let's do just that.
-        let intersection_point = ray.forwarded(tcenter - tdelta).pos;
-        Intersection::Hit(intersection_point)
+
+        let t = e2.dot(&qvec) * inv;
+        if t < t_min || t > t_max {
+            return Intersection::None;
+        }
+
+        Intersection::Hit {
+            t,
+            point: ray.forwarded(t).pos,
+            normal: e1.cross(&e2).normalized(),
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB {
+            min: Vector {
+                x: self.a.x.min(self.b.x).min(self.c.x),
+                y: self.a.y.min(self.b.y).min(self.c.y),
+                z: self.a.z.min(self.b.z).min(self.c.z),
+            },
+            max: Vector {
+                x: self.a.x.max(self.b.x).max(self.c.x),
+                y: self.a.y.max(self.b.y).max(self.c.y),
+                z: self.a.z.max(self.b.z).max(self.c.z),
+            },
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum Intersection {
     None,
-    Hit(Vector),
+    Hit {
+        t: f32,
+        point: Vector,
+        normal: Vector,
+    },
 }
 
 impl Intersection {
@@ -217,14 +370,222 @@ impl Intersection {
                 Intersection::None => true,
                 _ => false,
             },
-            Intersection::Hit(v1) => match other {
-                Intersection::Hit(v2) => v1.almost_equal(&v2),
+            Intersection::Hit {
+                t: t1,
+                point: p1,
+                normal: n1,
+            } => match other {
+                Intersection::Hit {
+                    t: t2,
+                    point: p2,
+                    normal: n2,
+                } => almost_equal(*t1, *t2) && p1.almost_equal(&p2) && n1.almost_equal(&n2),
                 _ => false,
             },
         }
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct AABB {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl AABB {
+    // Slab method: for each axis the ray enters and exits the pair of planes bounding the box at
+    // some t1 and t2; the box is hit iff the [tmin, tmax] intervals for all three axes overlap.
+    // Multiplying by the ray's precomputed inv_dir avoids a division per axis. For an
+    // axis-parallel ray (dir component == 0.0) that inv_dir component is +-infinity, so t1 and t2
+    // come out as +-infinity, and the min/max below sort that out without having to branch on the
+    // sign of the direction.
+    pub fn intersect(&self, ray: &Ray, t_max: f32) -> bool {
+        let mut tmin = 0.0f32;
+        let mut tmax = t_max;
+
+        let t1x = (self.min.x - ray.pos.x) * ray.inv_dir.x;
+        let t2x = (self.max.x - ray.pos.x) * ray.inv_dir.x;
+        tmin = tmin.max(t1x.min(t2x));
+        tmax = tmax.min(t1x.max(t2x));
+
+        let t1y = (self.min.y - ray.pos.y) * ray.inv_dir.y;
+        let t2y = (self.max.y - ray.pos.y) * ray.inv_dir.y;
+        tmin = tmin.max(t1y.min(t2y));
+        tmax = tmax.min(t1y.max(t2y));
+
+        let t1z = (self.min.z - ray.pos.z) * ray.inv_dir.z;
+        let t2z = (self.max.z - ray.pos.z) * ray.inv_dir.z;
+        tmin = tmin.max(t1z.min(t2z));
+        tmax = tmax.min(t1z.max(t2z));
+
+        tmax >= tmin.max(0.0) && tmin <= t_max
+    }
+
+    fn union(&self, other: &AABB) -> AABB {
+        AABB {
+            min: Vector {
+                x: self.min.x.min(other.min.x),
+                y: self.min.y.min(other.min.y),
+                z: self.min.z.min(other.min.z),
+            },
+            max: Vector {
+                x: self.max.x.max(other.max.x),
+                y: self.max.y.max(other.max.y),
+                z: self.max.z.max(other.max.z),
+            },
+        }
+    }
+
+    fn centroid(&self) -> Vector {
+        (self.min + self.max) / 2.0
+    }
+}
+
+// Primitives are moved into leaves wholesale once a subtree is small enough that a linear scan
+// beats the overhead of further splitting.
+const BVH_LEAF_SIZE: usize = 4;
+
+enum BvhNode {
+    Leaf {
+        bbox: AABB,
+        spheres: Vec<Sphere>,
+    },
+    Internal {
+        bbox: AABB,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &AABB {
+        match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Internal { bbox, .. } => bbox,
+        }
+    }
+
+    // Builds the hierarchy top-down: the primitives' boxes are unioned into the node's own box,
+    // then (unless we're already down to a handful of spheres) split at the median along the axis
+    // where their centroids are most spread out. This tends to produce well-balanced, tight-fitting
+    // trees without the bookkeeping a full surface-area-heuristic build would need.
+    fn build(mut spheres: Vec<Sphere>) -> BvhNode {
+        let bbox = spheres
+            .iter()
+            .map(|s| s.bounding_box())
+            .fold(None, |acc: Option<AABB>, b| {
+                Some(match acc {
+                    Some(acc) => acc.union(&b),
+                    None => b,
+                })
+            })
+            .expect("a BVH node needs at least one sphere");
+
+        if spheres.len() <= BVH_LEAF_SIZE {
+            return BvhNode::Leaf { bbox, spheres };
+        }
+
+        let centroids: Vec<Vector> = spheres
+            .iter()
+            .map(|s| s.bounding_box().centroid())
+            .collect();
+        let min = centroids.iter().fold(centroids[0], |acc, c| Vector {
+            x: acc.x.min(c.x),
+            y: acc.y.min(c.y),
+            z: acc.z.min(c.z),
+        });
+        let max = centroids.iter().fold(centroids[0], |acc, c| Vector {
+            x: acc.x.max(c.x),
+            y: acc.y.max(c.y),
+            z: acc.z.max(c.z),
+        });
+        let extent = max - min;
+
+        let axis_is_x = extent.x >= extent.y && extent.x >= extent.z;
+        let axis_is_y = !axis_is_x && extent.y >= extent.z;
+        if axis_is_x {
+            spheres.sort_by(|a, b| {
+                a.bounding_box()
+                    .centroid()
+                    .x
+                    .partial_cmp(&b.bounding_box().centroid().x)
+                    .unwrap()
+            });
+        } else if axis_is_y {
+            spheres.sort_by(|a, b| {
+                a.bounding_box()
+                    .centroid()
+                    .y
+                    .partial_cmp(&b.bounding_box().centroid().y)
+                    .unwrap()
+            });
+        } else {
+            spheres.sort_by(|a, b| {
+                a.bounding_box()
+                    .centroid()
+                    .z
+                    .partial_cmp(&b.bounding_box().centroid().z)
+                    .unwrap()
+            });
+        }
+
+        let mid = spheres.len() / 2;
+        let right_spheres = spheres.split_off(mid);
+        let left = Box::new(BvhNode::build(spheres));
+        let right = Box::new(BvhNode::build(right_spheres));
+        BvhNode::Internal { bbox, left, right }
+    }
+
+    fn intersect_ray(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        if !self.bbox().intersect(ray, t_max) {
+            return Intersection::None;
+        }
+        match self {
+            BvhNode::Leaf { spheres, .. } => {
+                let mut closest = Intersection::None;
+                let mut closest_t = t_max;
+                for sphere in spheres {
+                    if let Intersection::Hit { t, point, normal } =
+                        sphere.intersect_ray(ray, t_min, closest_t)
+                    {
+                        closest_t = t;
+                        closest = Intersection::Hit { t, point, normal };
+                    }
+                }
+                closest
+            }
+            BvhNode::Internal { left, right, .. } => {
+                let left_hit = left.intersect_ray(ray, t_min, t_max);
+                let closest_t = match left_hit {
+                    Intersection::Hit { t, .. } => t,
+                    Intersection::None => t_max,
+                };
+                let right_hit = right.intersect_ray(ray, t_min, closest_t);
+                match right_hit {
+                    Intersection::None => left_hit,
+                    _ => right_hit,
+                }
+            }
+        }
+    }
+}
+
+pub struct Bvh {
+    root: BvhNode,
+}
+
+impl Bvh {
+    pub fn new(spheres: Vec<Sphere>) -> Bvh {
+        Bvh {
+            root: BvhNode::build(spheres),
+        }
+    }
+
+    pub fn intersect_ray(&self, ray: &Ray) -> Intersection {
+        self.root.intersect_ray(ray, 0.0001, f32::INFINITY)
+    }
+}
+
 pub fn almost_equal(a: f32, b: f32) -> bool {
     almost_equal_with_epsilon(a, b, 0.0000001)
 }
@@ -243,6 +604,34 @@ pub struct Camera {
 }
 
 impl Camera {
+    // Builds the camera's orthonormal basis the way a graphics lookAt would, instead of requiring
+    // the caller to hand over already-normalized and mutually consistent forward/up vectors.
+    pub fn look_at(
+        eye: Vector,
+        target: Vector,
+        up: Vector,
+        fovx: Radians,
+        aspect_ratio: f32,
+    ) -> Camera {
+        let forward = (target - eye).normalized();
+        let right = forward.cross(&up);
+        // If up is parallel to forward the cross product degenerates to the zero vector and there
+        // is no well-defined basis to build.
+        assert!(
+            right.len() > 0.0000001,
+            "up must not be parallel to the eye-to-target direction"
+        );
+        let right = right.normalized();
+        let true_up = right.cross(&forward);
+        Camera {
+            position: eye,
+            forward,
+            up: true_up,
+            aspect_ratio,
+            fovx,
+        }
+    }
+
     pub fn screen_ray(&self, x: f32, y: f32) -> Ray {
         // We assume that a screen lies 1 unit in front of the camera. The center (x: 0.5, y: 0.5) of the screen
         // lies directly on the forward axis.
@@ -265,10 +654,10 @@ impl Camera {
             + self.forward
             + right * xunit * screen_width / 2.0
             + self.up * yunit * screen_height / 2.0;
-        let ray = Ray {
-            pos: self.position,
-            dir: (point_at_screen - self.position).normalized(),
-        };
+        let ray = Ray::new(
+            self.position,
+            (point_at_screen - self.position).normalized(),
+        );
         ray
     }
 }